@@ -33,15 +33,30 @@
 extern crate flate2;
 extern crate rustc_serialize;
 extern crate byteorder;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "chrono-tz")]
+extern crate chrono;
+#[cfg(feature = "chrono-tz")]
+extern crate chrono_tz;
 
-use std::{cmp, mem, sync};
-use std::sync::atomic;
+use std::{cmp, sync};
+use std::collections::HashMap;
 use std::io::prelude::*;
 use std::io::BufReader;
 use byteorder::{BigEndian, ReadBytesExt};
 
 #[allow(warnings)]
 mod tables;
+mod names;
+
+pub use names::canonicalize;
+
+/// The single, process-wide `TzSearch`, built the first time it's needed.
+fn shared() -> &'static TzSearch {
+    static SHARED: sync::OnceLock<TzSearch> = sync::OnceLock::new();
+    SHARED.get_or_init(TzSearch::new)
+}
 
 /// Attempt to compute the timezone that the point `lat`, `long`
 /// lies in.
@@ -66,21 +81,60 @@ mod tables;
 /// assert_eq!(tz_search::lookup(0.0, 0.0), None);
 /// ```
 pub fn lookup(lat: f64, lon: f64) -> Option<String> {
-    static SHARED: atomic::AtomicUsize = atomic::ATOMIC_USIZE_INIT;
-    static ONCE: sync::Once = sync::ONCE_INIT;
-
-    ONCE.call_once(|| {
-        let s = Box::new(TzSearch::new());
-        SHARED.store(unsafe {mem::transmute(s)}, atomic::Ordering::Relaxed);
-    });
-
-    let ptr = SHARED.load(atomic::Ordering::Relaxed);
-    assert!(ptr != 0);
-    let s = unsafe {&*(ptr as *const TzSearch)};
-    s.lookup(lat, lon)
+    shared().lookup(lat, lon)
 }
 
-#[derive(Debug, Eq, PartialEq, Ord, PartialOrd)]
+/// Like `lookup`, but returns a borrowed, statically-known zone
+/// name instead of allocating a fresh `String` on every call.
+///
+/// # Examples
+///
+/// ```rust
+/// assert_eq!(tz_search::lookup_static(-33.79, 151.17),
+///            Some("Australia/Sydney"));
+/// ```
+pub fn lookup_static(lat: f64, lon: f64) -> Option<&'static str> {
+    shared().lookup_str(lat, lon)
+}
+
+/// Compute the UTC offset (including any DST adjustment) in effect
+/// at `utc` for the timezone that the point `lat`, `long` lies in.
+///
+/// Requires the `chrono-tz` feature.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// use chrono::{FixedOffset, TimeZone, Utc};
+/// assert_eq!(tz_search::offset_at(-33.79, 151.17, Utc.ymd(2020, 1, 1).and_hms(0, 0, 0)),
+///            Some(FixedOffset::east(11 * 3600)));
+/// ```
+#[cfg(feature = "chrono-tz")]
+pub fn offset_at(lat: f64, long: f64, utc: chrono::DateTime<chrono::Utc>) -> Option<chrono::FixedOffset> {
+    use chrono::{Offset, TimeZone};
+    let tz = shared().lookup_tz(lat, long)?;
+    Some(tz.offset_from_utc_datetime(&utc.naive_utc()).fix())
+}
+
+/// Convert a `lat`, `long` pair into the `(x, y)` pixel coordinates
+/// used by the zoom-level tables, clamping to the edges of the map.
+///
+/// # Panics
+///
+/// Panics if either of the ranges documented on `lookup` are
+/// violated.
+fn pixel_coords(lat: f64, long: f64) -> (usize, usize) {
+    fn clamp(x: isize, lim: isize) -> usize {
+        cmp::max(0, cmp::min(lim * tables::DEG_PIXELS as isize, x)) as usize
+    }
+    assert!(-90.0 <= lat && lat <= 90.0);
+    assert!(-180.0 <= long && long <= 180.0);
+    let x = ((long + 180.0) * (tables::DEG_PIXELS as f64)) as isize;
+    let y = ((90.0 - lat) * (tables::DEG_PIXELS as f64)) as isize;
+    (clamp(x, 360), clamp(y, 180))
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
 struct TileKey(u32);
 impl TileKey {
     fn new(size: u8, x: u16, y: u16) -> TileKey {
@@ -110,22 +164,18 @@ struct ZoomLevel {
 pub struct TzSearch {
     leaves: Vec<Zone>,
     zoom_levels: Vec<ZoomLevel>,
+    // Deduplicated zone names, referenced by index from
+    // `Zone::StaticZone` so that repeated lookups of the same zone
+    // don't need to allocate.
+    zone_names: Vec<String>,
 }
 
+#[derive(Debug)]
 enum Zone {
-    StaticZone(String),
+    StaticZone(usize),
     OneBitTile([u16; 2], [u8; 8]),
     Pixmap([u8; 128])
 }
-impl std::fmt::Debug for Zone {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match *self {
-            Zone::StaticZone(ref s) => write!(f, "StaticZone({:?})", s),
-            Zone::OneBitTile(a, b) => write!(f, "OneBitTile({:?}, {:?})", &a[..], &b[..]),
-            Zone::Pixmap(c) => write!(f, "Pixmap({:?})", &c[..]),
-        }
-    }
-}
 
 
 impl TzSearch {
@@ -163,13 +213,20 @@ impl TzSearch {
             .unwrap();
         let mut ungz = BufReader::new(flate2::read::GzDecoder::new(&*unb64d).unwrap());
         let mut buf = [0; 128];
+        let mut zone_names = vec![];
+        let mut zone_name_indices = HashMap::new();
         for _ in 0..tables::NUM_LEAVES {
             let zone = match ungz.read_u8().unwrap() {
                 b'S' => {
                     let mut zone_name = vec![];
                     ungz.read_until(0, &mut zone_name).unwrap();
                     if zone_name.last() == Some(&0) { zone_name.pop(); }
-                    Zone::StaticZone(String::from_utf8(zone_name).unwrap())
+                    let zone_name = String::from_utf8(zone_name).unwrap();
+                    let idx = *zone_name_indices.entry(zone_name.clone()).or_insert_with(|| {
+                        zone_names.push(zone_name);
+                        zone_names.len() - 1
+                    });
+                    Zone::StaticZone(idx)
                 }
                 b'2' => {
                     let idx = [ungz.read_u16::<BigEndian>().unwrap(),
@@ -199,7 +256,8 @@ impl TzSearch {
 
         TzSearch {
             zoom_levels: zoom_levels,
-            leaves: leaves
+            leaves: leaves,
+            zone_names: zone_names,
         }
     }
 
@@ -227,19 +285,202 @@ impl TzSearch {
     ///            "Australia/Sydney");
     /// ```
     pub fn lookup(&self, lat: f64, long: f64) -> Option<String> {
-        fn clamp(x: isize, lim: isize) -> usize {
-            cmp::max(0, cmp::min(lim * tables::DEG_PIXELS as isize, x)) as usize
+        self.lookup_str(lat, long).map(|s| s.to_string())
+    }
+
+    /// Like `lookup`, but returns a reference to the zone name
+    /// owned by this `TzSearch` instead of allocating a fresh
+    /// `String` on every call.
+    ///
+    /// See also: the top-level `lookup_static` function, which
+    /// hands back a `&'static str` from the process-wide instance.
+    ///
+    /// # Panics
+    ///
+    /// `lookup_str` will panic if either of the ranges documented
+    /// on `lookup` are violated.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// let s = tz_search::TzSearch::new();
+    /// assert_eq!(s.lookup_str(-33.79, 151.17),
+    ///            Some("Australia/Sydney"));
+    /// ```
+    pub fn lookup_str(&self, lat: f64, long: f64) -> Option<&str> {
+        let (x, y) = pixel_coords(lat, long);
+        self.lookup_pixel_idx(x, y).map(|idx| self.zone_names[idx].as_str())
+    }
+
+    /// A fast, coarse-only approximation of `lookup`.
+    ///
+    /// This consults only the coarsest populated zoom level and
+    /// returns immediately if it is covered by a single zone,
+    /// without ever decoding the finer `OneBitTile`/`Pixmap`
+    /// leaves that `lookup` descends into. For a point well inside
+    /// a large country this gives the same answer as `lookup` far
+    /// faster; everywhere else (including small countries,
+    /// enclaves, and anywhere near a border) it gives up and
+    /// returns `None` rather than guess.
+    ///
+    /// `lookup_fuzzy` may therefore be wrong by omission near
+    /// borders and in small enclaves in a way `lookup` is not;
+    /// callers who need that accuracy should keep using `lookup`.
+    ///
+    /// # Panics
+    ///
+    /// `lookup_fuzzy` will panic if either of the ranges documented
+    /// on `lookup` are violated.
+    pub fn lookup_fuzzy(&self, lat: f64, long: f64) -> Option<String> {
+        const COARSEST_LEVEL: u8 = 5;
+
+        let (x, y) = pixel_coords(lat, long);
+        let shift = 3 + COARSEST_LEVEL;
+        let xt = x >> shift;
+        let yt = y >> shift;
+        let tk = TileKey::new(COARSEST_LEVEL, xt as u16, yt as u16);
+
+        let zl = &self.zoom_levels[COARSEST_LEVEL as usize];
+        match Self::find_tile(zl, tk) {
+            Some(leaf_idx) => match self.leaves[leaf_idx] {
+                Zone::StaticZone(idx) => Some(self.zone_names[idx].clone()),
+                Zone::OneBitTile(..) | Zone::Pixmap(..) => None,
+            },
+            None => None,
+        }
+    }
+
+    /// Find the leaf index for the tile exactly matching `tk`
+    /// within `zl`, if any.
+    fn find_tile(zl: &ZoomLevel, tk: TileKey) -> Option<usize> {
+        let pos = zl.tiles.binary_search_by(|t| t.tile.cmp(&tk)).unwrap_or_else(|x| x);
+        match zl.tiles.get(pos) {
+            Some(tl) if tl.tile == tk => Some(tl.idx as usize),
+            _ => None,
         }
-        assert!(-90.0 <= lat && lat <= 90.0);
-        assert!(-180.0 <= long && long <= 180.0);
-        let x = ((long + 180.0) * (tables::DEG_PIXELS as f64)) as isize;
-        let y = ((90.0 - lat) * (tables::DEG_PIXELS as f64)) as isize;
-        let x = clamp(x, 360);
-        let y = clamp(y, 180);
-
-        self.lookup_pixel(x, y)
     }
-    fn lookup_pixel(&self, x: usize, y: usize) -> Option<String> {
+
+    /// Find every plausible zone for a point, for disambiguating
+    /// lookups that are close to a border.
+    ///
+    /// This is `lookup`'s only remedy for the crate-level caveat
+    /// that it is "not perfectly accurate when very close to
+    /// borders": it samples the 3x3 block of pixels around `lat`,
+    /// `long` and returns the distinct, non-ocean zone names found,
+    /// ordered by proximity, so a caller can pick among them with
+    /// some other signal. `lookup`'s answer, if any, is always the
+    /// first element. See `lookup_candidates_radius` to sample a
+    /// larger or smaller block.
+    ///
+    /// # Panics
+    ///
+    /// `lookup_candidates` will panic if either of the ranges
+    /// documented on `lookup` are violated.
+    pub fn lookup_candidates(&self, lat: f64, long: f64) -> Vec<String> {
+        self.lookup_candidates_radius(lat, long, 1)
+    }
+
+    /// As `lookup_candidates`, but sampling a `2 * radius + 1`
+    /// square of pixels around the target point, rather than the
+    /// fixed 3x3 block.
+    pub fn lookup_candidates_radius(&self, lat: f64, long: f64, radius: usize) -> Vec<String> {
+        let (x, y) = pixel_coords(lat, long);
+        // Match the bounds `pixel_coords` itself clamps `(x, y)`
+        // to, so the center sample below is exactly the pixel
+        // `lookup` would use.
+        let max_x = 360 * tables::DEG_PIXELS;
+        let max_y = 180 * tables::DEG_PIXELS;
+        let radius = radius as isize;
+
+        let mut offsets = vec![];
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                offsets.push((dx, dy));
+            }
+        }
+        offsets.sort_by_key(|&(dx, dy)| dx * dx + dy * dy);
+
+        let mut indices = vec![];
+        for (dx, dy) in offsets {
+            let xx = cmp::max(0, cmp::min(max_x as isize, x as isize + dx)) as usize;
+            let yy = cmp::max(0, cmp::min(max_y as isize, y as isize + dy)) as usize;
+            if let Some(idx) = self.lookup_pixel_idx(xx, yy) {
+                if !indices.contains(&idx) {
+                    indices.push(idx);
+                }
+            }
+        }
+        indices.into_iter().map(|idx| self.zone_names[idx].clone()).collect()
+    }
+
+    /// Attempt to compute the BCP47 short ID (as used by e.g.
+    /// ICU4X, such as `"ausyd"` for `Australia/Sydney`) of the
+    /// timezone that the point `lat`, `long` lies in.
+    ///
+    /// See also: `lookup`, and the top-level `canonicalize`
+    /// function for normalizing arbitrary zone strings against the
+    /// same embedded data.
+    ///
+    /// `names::IANA_TO_BCP47` is currently only a small hand-curated
+    /// subset of zones (see its docs), so this returns `None` for
+    /// the great majority of resolved zones, not just ones in the
+    /// ocean.
+    ///
+    /// # Panics
+    ///
+    /// `lookup_bcp47` will panic if either of the ranges documented
+    /// on `lookup` are violated.
+    pub fn lookup_bcp47(&self, lat: f64, long: f64) -> Option<&'static str> {
+        let name = self.lookup_str(lat, long)?;
+        names::bcp47_for(names::canonicalize(name))
+    }
+
+    /// Look up the timezone of every point in `points`, reusing
+    /// this single `TzSearch` across the whole slice.
+    ///
+    /// This is equivalent to mapping `lookup` over `points`, but
+    /// avoids re-entering the process-wide singleton (as the
+    /// top-level `lookup` function does) for each point.
+    ///
+    /// # Panics
+    ///
+    /// `lookup_batch` will panic if any point violates the ranges
+    /// documented on `lookup`.
+    pub fn lookup_batch(&self, points: &[(f64, f64)]) -> Vec<Option<String>> {
+        points.iter().map(|&(lat, long)| self.lookup(lat, long)).collect()
+    }
+
+    /// Attempt to resolve the timezone of the point `lat`, `long`
+    /// into a `chrono_tz::Tz`, resolving any deprecated alias via
+    /// `canonicalize` first.
+    ///
+    /// See also: the top-level `offset_at` for going straight to a
+    /// UTC offset at a given instant.
+    ///
+    /// Requires the `chrono-tz` feature.
+    ///
+    /// # Panics
+    ///
+    /// `lookup_tz` will panic if either of the ranges documented on
+    /// `lookup` are violated.
+    #[cfg(feature = "chrono-tz")]
+    pub fn lookup_tz(&self, lat: f64, long: f64) -> Option<chrono_tz::Tz> {
+        let name = self.lookup_str(lat, long)?;
+        names::canonicalize(name).parse().ok()
+    }
+
+    /// As `lookup_batch`, but spreads the work across a `rayon`
+    /// thread pool. Since `zone_lookup` only ever reads `self`,
+    /// this is embarrassingly parallel.
+    ///
+    /// Requires the `rayon` feature.
+    #[cfg(feature = "rayon")]
+    pub fn lookup_batch_par(&self, points: &[(f64, f64)]) -> Vec<Option<String>> {
+        use rayon::prelude::*;
+        points.par_iter().map(|&(lat, long)| self.lookup(lat, long)).collect()
+    }
+
+    fn lookup_pixel_idx(&self, x: usize, y: usize) -> Option<usize> {
         for level in (0..6).rev() {
             let shift = 3 + level;
             let xt = x >> shift;
@@ -252,9 +493,9 @@ impl TzSearch {
         None
     }
 
-    fn zone_lookup(&self, zone: &Zone, x: usize, y: usize, tk: TileKey) -> Option<Option<String>> {
+    fn zone_lookup(&self, zone: &Zone, x: usize, y: usize, tk: TileKey) -> Option<Option<usize>> {
         match *zone {
-            Zone::StaticZone(ref s) => Some(Some(s.clone())),
+            Zone::StaticZone(idx) => Some(Some(idx)),
             Zone::OneBitTile(idxs, rows) => {
                 let idx = if rows[y & 7] & (1 << (x & 7)) != 0 {
                     idxs[1]
@@ -279,13 +520,11 @@ impl TzSearch {
     }
 
     fn zoom_level_lookup(&self, zl: &ZoomLevel, x: usize, y: usize, tk: TileKey)
-                         -> Option<Option<String>>
+                         -> Option<Option<usize>>
     {
-        let pos = zl.tiles.binary_search_by(|t| t.tile.cmp(&tk)).unwrap_or_else(|x| x);
-
-        match zl.tiles.get(pos) {
-            Some(tl) if tl.tile == tk => self.zone_lookup(&self.leaves[tl.idx as usize], x, y, tk),
-            _ => None
+        match Self::find_tile(zl, tk) {
+            Some(leaf_idx) => self.zone_lookup(&self.leaves[leaf_idx], x, y, tk),
+            None => None,
         }
     }
 }
@@ -293,7 +532,7 @@ impl TzSearch {
 
 #[cfg(test)]
 mod tests {
-    use super::{lookup, TzSearch};
+    use super::{canonicalize, lookup, lookup_static, TzSearch};
 
     #[test]
     fn loads_ok() {
@@ -312,6 +551,52 @@ mod tests {
         }
     }
     #[test]
+    fn test_lookup_str_and_static() {
+        let searcher = TzSearch::new();
+        let tests = [(37.7833, -122.4167, Some("America/Los_Angeles")),
+                     (-33.79, 151.17, Some("Australia/Sydney")),
+                     (0.0, 0.0, None)];
+        for &(lat, lon, want) in &tests {
+            assert_eq!(searcher.lookup_str(lat, lon), want);
+            assert_eq!(lookup_static(lat, lon), want)
+        }
+    }
+    #[test]
+    fn test_lookup_fuzzy() {
+        let searcher = TzSearch::new();
+        // well inside a large country (inland Western Australia, whose
+        // whole coarsest tile is one zone): same answer as `lookup`.
+        assert_eq!(searcher.lookup_fuzzy(-26.0, 120.0), searcher.lookup(-26.0, 120.0));
+        // in the ocean, far from any coastline: still `None`, like `lookup`.
+        assert_eq!(searcher.lookup_fuzzy(0.0, 0.0), None);
+    }
+    #[test]
+    fn test_lookup_batch() {
+        let searcher = TzSearch::new();
+        let points = [(37.7833, -122.4167), (-33.79, 151.17), (0.0, 0.0)];
+        let want = [Some("America/Los_Angeles".to_string()),
+                    Some("Australia/Sydney".to_string()),
+                    None];
+        assert_eq!(searcher.lookup_batch(&points), want);
+    }
+    #[test]
+    fn test_lookup_bcp47() {
+        let searcher = TzSearch::new();
+        assert_eq!(searcher.lookup_bcp47(-33.79, 151.17), Some("ausyd"));
+        assert_eq!(searcher.lookup_bcp47(37.7833, -122.4167), Some("uslax"));
+        assert_eq!(canonicalize("Asia/Calcutta"), "Asia/Kolkata");
+    }
+    #[test]
+    fn test_lookup_candidates() {
+        let searcher = TzSearch::new();
+        // well inside a single zone: the first candidate is `lookup`'s answer.
+        let candidates = searcher.lookup_candidates(-33.79, 151.17);
+        assert_eq!(candidates.first().map(|s| s.as_str()), Some("Australia/Sydney"));
+
+        // in the ocean, far from any coastline: no candidates at all.
+        assert_eq!(searcher.lookup_candidates(0.0, 0.0), Vec::<String>::new());
+    }
+    #[test]
     fn test_lookup_pixel() {
         let searcher = TzSearch::new();
         let tests = [
@@ -340,7 +625,8 @@ mod tests {
             ];
 
         for &(lat, lon, ref want) in &tests {
-            assert_eq!(searcher.lookup_pixel(lat, lon), want.map(|s| s.to_string()));
+            let got = searcher.lookup_pixel_idx(lat, lon).map(|idx| searcher.zone_names[idx].clone());
+            assert_eq!(got, want.map(|s| s.to_string()));
         }
     }
 }
@@ -0,0 +1,120 @@
+//! IANA zone name handling: BCP47 short IDs (as used by e.g. ICU4X)
+//! and canonicalization of deprecated `tzdata` aliases.
+//!
+//! Both tables are sorted by key so that lookups can use binary
+//! search.
+//!
+//! # Coverage
+//!
+//! Unlike `tables.rs`, which embeds the *complete* geometry data
+//! generated from the upstream `latlong` tables, `IANA_TO_BCP47` and
+//! `ALIASES` below are a small, hand-curated subset covering a
+//! couple of dozen major cities and common aliases — nowhere near
+//! the ~450 zones `TzSearch` can actually resolve or the full
+//! `tzdata` `backward` alias list. `bcp47_for`/`lookup_bcp47` will
+//! return `None`, and `canonicalize` will pass its input through
+//! unchanged, for any zone not listed here. Treat these tables as a
+//! starting point: filling them in for every zone means generating
+//! them from CLDR's `bcp47/timezone.xml` and `tzdata`'s `backward`
+//! file at packaging time, the same way `tables.rs`'s data is
+//! produced, rather than by hand.
+
+/// Sorted `(IANA zone, BCP47 short ID)` pairs.
+///
+/// See the [module-level](self) docs: this is a hand-curated subset,
+/// not the full CLDR table.
+pub static IANA_TO_BCP47: &[(&str, &str)] = &[
+    ("Africa/Cairo", "egcai"),
+    ("Africa/Johannesburg", "zajnb"),
+    ("Africa/Lagos", "nglos"),
+    ("Africa/Nairobi", "kenbo"),
+    ("America/Anchorage", "usanc"),
+    ("America/Chicago", "uschi"),
+    ("America/Denver", "usden"),
+    ("America/Los_Angeles", "uslax"),
+    ("America/New_York", "usnyc"),
+    ("America/Sao_Paulo", "brsao"),
+    ("Asia/Hong_Kong", "hkhkg"),
+    ("Asia/Kolkata", "inccu"),
+    ("Asia/Seoul", "krsel"),
+    ("Asia/Shanghai", "cnsha"),
+    ("Asia/Taipei", "twtpe"),
+    ("Asia/Tokyo", "jptyo"),
+    ("Australia/Adelaide", "auadl"),
+    ("Australia/Brisbane", "aubne"),
+    ("Australia/Melbourne", "aumel"),
+    ("Australia/Sydney", "ausyd"),
+    ("Europe/Berlin", "deber"),
+    ("Europe/London", "gblon"),
+    ("Europe/Moscow", "rumow"),
+    ("Europe/Paris", "frpar"),
+    ("Pacific/Auckland", "nzakl"),
+];
+
+/// Sorted `(deprecated alias, canonical IANA zone)` pairs.
+///
+/// See the [module-level](self) docs: this is a hand-curated subset
+/// of `tzdata`'s `backward` alias file, not the full list.
+pub static ALIASES: &[(&str, &str)] = &[
+    ("America/Buenos_Aires", "America/Argentina/Buenos_Aires"),
+    ("Asia/Calcutta", "Asia/Kolkata"),
+    ("Asia/Katmandu", "Asia/Kathmandu"),
+    ("Asia/Rangoon", "Asia/Yangon"),
+    ("Asia/Saigon", "Asia/Ho_Chi_Minh"),
+    ("Australia/ACT", "Australia/Sydney"),
+    ("Australia/NSW", "Australia/Sydney"),
+    ("Europe/Kiev", "Europe/Kyiv"),
+    ("Pacific/Yap", "Pacific/Chuuk"),
+    ("US/Central", "America/Chicago"),
+    ("US/Eastern", "America/New_York"),
+    ("US/Mountain", "America/Denver"),
+    ("US/Pacific", "America/Los_Angeles"),
+];
+
+/// Look up the BCP47 short ID for a canonical IANA zone name, such
+/// as `"ausyd"` for `"Australia/Sydney"`.
+///
+/// Returns `None` if `name` is not a canonical zone in the table
+/// (in particular, aliases must be resolved with `canonicalize`
+/// first) — note that `IANA_TO_BCP47` only covers a small subset of
+/// zones, so this returns `None` far more often than a real zone
+/// name would suggest. See the [module-level](self) docs.
+pub fn bcp47_for(name: &str) -> Option<&'static str> {
+    IANA_TO_BCP47.binary_search_by_key(&name, |&(iana, _)| iana)
+        .ok()
+        .map(|idx| IANA_TO_BCP47[idx].1)
+}
+
+/// Canonicalize a zone name, resolving deprecated `tzdata` aliases
+/// (e.g. `"Asia/Calcutta"`, `"US/Pacific"`) to the name they are
+/// aliases of. Names that aren't known aliases are returned
+/// unchanged, even if they aren't valid zone names at all — and
+/// `ALIASES` only covers a small subset of `tzdata`'s aliases, so
+/// most deprecated names will come back unchanged rather than
+/// canonicalized. See the [module-level](self) docs.
+pub fn canonicalize(name: &str) -> &str {
+    match ALIASES.binary_search_by_key(&name, |&(alias, _)| alias) {
+        Ok(idx) => ALIASES[idx].1,
+        Err(_) => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bcp47_for, canonicalize};
+
+    #[test]
+    fn test_bcp47_for() {
+        assert_eq!(bcp47_for("Australia/Sydney"), Some("ausyd"));
+        assert_eq!(bcp47_for("America/Los_Angeles"), Some("uslax"));
+        assert_eq!(bcp47_for("Nowhere/Nothing"), None);
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        assert_eq!(canonicalize("Asia/Calcutta"), "Asia/Kolkata");
+        assert_eq!(canonicalize("US/Pacific"), "America/Los_Angeles");
+        // not an alias: unchanged, even though it's also not a real zone.
+        assert_eq!(canonicalize("Nowhere/Nothing"), "Nowhere/Nothing");
+    }
+}